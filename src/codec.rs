@@ -0,0 +1,235 @@
+//! Runtime format selection.
+//!
+//! Everywhere else in this crate a caller picks a format at the type level (`ParCompress<Zstd,
+//! _>`, `ParCompress<Bgzf, _>`, ...), which is great when the format is known at compile time but
+//! unworkable for a tool that must support whatever extension a user's input file happens to
+//! have. [`CompressionFormat`] is a small runtime registry, modeled on the codec registries in
+//! `parquet` and `rust-installer`, that maps between file extensions and formats and can build a
+//! dynamically dispatched parallel writer for the format it names.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::bgzf::Bgzf;
+use crate::gzip::Gzip;
+use crate::mgzip::Mgzip;
+use crate::par::compress::ParCompressBuilder;
+use crate::snap::Snap;
+use crate::xz::Xz;
+use crate::zstd::{self, Zstd};
+use crate::{GzpError, ZWriter};
+
+/// A boxable combination of [`Write`] and [`ZWriter`], since a bare `Box<dyn ZWriter<W>>` would
+/// let go of the ability to actually write to it. Blanket-implemented for every writer that is
+/// both, so callers of [`CompressionFormat::par_writer`] get one trait object they can both
+/// `write_all` into and `finish`.
+pub trait ParWriter<W>: Write + ZWriter<W> {}
+
+impl<T, W> ParWriter<W> for T where T: Write + ZWriter<W> {}
+
+/// A compression format gzp knows how to parallel-compress, chosen at runtime rather than at the
+/// type level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionFormat {
+    /// Plain gzip.
+    Gzip,
+    /// Blocked gzip (BGZF), as used by `samtools`/`htslib`.
+    Bgzf,
+    /// Multithreaded gzip.
+    Mgzip,
+    /// Google's Snappy format.
+    Snap,
+    /// Zstandard.
+    Zstd,
+    /// xz / LZMA.
+    Xz,
+}
+
+impl CompressionFormat {
+    /// Infer a format from a path's extension, e.g. `.gz` -> [`CompressionFormat::Gzip`].
+    ///
+    /// Returns `None` if the extension is missing or unrecognized; callers typically fall back
+    /// to a default format (commonly [`CompressionFormat::Gzip`]) in that case.
+    pub fn detect_from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?;
+        Some(match ext {
+            "gz" => Self::Gzip,
+            "bgzf" | "bgz" => Self::Bgzf,
+            "mgz" => Self::Mgzip,
+            "sz" | "snappy" => Self::Snap,
+            "zst" => Self::Zstd,
+            "xz" => Self::Xz,
+            _ => return None,
+        })
+    }
+
+    /// The canonical file extension (without the leading dot) for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Bgzf => "bgzf",
+            Self::Mgzip => "mgz",
+            Self::Snap => "sz",
+            Self::Zstd => "zst",
+            Self::Xz => "xz",
+        }
+    }
+
+    /// Whether this format has a parallel decompressor available via [`CompressionFormat::par_decompress`].
+    ///
+    /// Only [`CompressionFormat::Zstd`] has one; every other format, including `Xz`, returns
+    /// `false`.
+    pub fn supports_par_decompress(&self) -> bool {
+        matches!(self, Self::Zstd)
+    }
+
+    /// Parallel-decompress `reader` into `writer` using this format's decompressor.
+    ///
+    /// Returns an error if [`CompressionFormat::supports_par_decompress`] is `false` for this
+    /// format.
+    pub fn par_decompress<R, W>(&self, reader: R, writer: W) -> Result<(), GzpError>
+    where
+        R: Read,
+        W: Write,
+    {
+        match self {
+            Self::Zstd => zstd::ZstdParDecompressBuilder::new()
+                .from_reader(reader)
+                .decompress(writer),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("no parallel decompressor implemented for {self:?} yet"),
+            )
+            .into()),
+        }
+    }
+
+    /// Build a parallel compressor for this format that writes to `writer`, boxed as a
+    /// [`ParWriter`] trait object so the concrete format need not be named at the call site.
+    ///
+    /// `num_threads` controls the size of the rayon pool each writer compresses blocks on.
+    pub fn par_writer<W>(
+        &self,
+        writer: W,
+        num_threads: usize,
+    ) -> Result<Box<dyn ParWriter<W> + Send>, GzpError>
+    where
+        W: std::io::Write + Send + 'static,
+    {
+        Ok(match self {
+            Self::Gzip => Box::new(
+                ParCompressBuilder::<Gzip>::new()
+                    .num_threads(num_threads)?
+                    .from_writer(writer),
+            ),
+            Self::Bgzf => Box::new(
+                ParCompressBuilder::<Bgzf>::new()
+                    .num_threads(num_threads)?
+                    .from_writer(writer),
+            ),
+            Self::Mgzip => Box::new(
+                ParCompressBuilder::<Mgzip>::new()
+                    .num_threads(num_threads)?
+                    .from_writer(writer),
+            ),
+            Self::Snap => Box::new(
+                ParCompressBuilder::<Snap>::new()
+                    .num_threads(num_threads)?
+                    .from_writer(writer),
+            ),
+            Self::Zstd => Box::new(
+                ParCompressBuilder::<Zstd>::new()
+                    .num_threads(num_threads)?
+                    .from_writer(writer),
+            ),
+            Self::Xz => Box::new(
+                ParCompressBuilder::<Xz>::new()
+                    .num_threads(num_threads)?
+                    .from_writer(writer),
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_detect_from_path() {
+        assert_eq!(
+            CompressionFormat::detect_from_path(Path::new("reads.fastq.gz")),
+            Some(CompressionFormat::Gzip)
+        );
+        assert_eq!(
+            CompressionFormat::detect_from_path(Path::new("alignments.bam.bgzf")),
+            Some(CompressionFormat::Bgzf)
+        );
+        assert_eq!(
+            CompressionFormat::detect_from_path(Path::new("data.parquet.zst")),
+            Some(CompressionFormat::Zstd)
+        );
+        assert_eq!(
+            CompressionFormat::detect_from_path(Path::new("archive.xz")),
+            Some(CompressionFormat::Xz)
+        );
+        assert_eq!(
+            CompressionFormat::detect_from_path(Path::new("plain.txt")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_par_writer_compresses() {
+        let input = b"This is a first test line\nThis is a second test line\n";
+
+        let mut writer = CompressionFormat::Zstd.par_writer(Vec::new(), 1).unwrap();
+        writer.write_all(input).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let mut decoder = ::zstd::Decoder::new(&compressed[..]).unwrap();
+        let mut result = vec![];
+        decoder.read_to_end(&mut result).unwrap();
+
+        assert_eq!(input.to_vec(), result);
+    }
+
+    #[test]
+    fn test_par_decompress_roundtrip() {
+        let input = b"This is a first test line\nThis is a second test line\n";
+
+        let mut writer = CompressionFormat::Zstd.par_writer(Vec::new(), 1).unwrap();
+        writer.write_all(input).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let mut result = vec![];
+        CompressionFormat::Zstd
+            .par_decompress(&compressed[..], &mut result)
+            .unwrap();
+
+        assert_eq!(input.to_vec(), result);
+    }
+
+    #[test]
+    fn test_par_decompress_unsupported_format() {
+        assert!(!CompressionFormat::Xz.supports_par_decompress());
+        assert!(CompressionFormat::Xz.par_decompress(&b""[..], Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_extension_roundtrip() {
+        for format in [
+            CompressionFormat::Gzip,
+            CompressionFormat::Bgzf,
+            CompressionFormat::Mgzip,
+            CompressionFormat::Snap,
+            CompressionFormat::Zstd,
+            CompressionFormat::Xz,
+        ] {
+            let path = Path::new("input").with_extension(format.extension());
+            assert_eq!(CompressionFormat::detect_from_path(&path), Some(format));
+        }
+    }
+}
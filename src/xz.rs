@@ -0,0 +1,185 @@
+//! xz / LZMA compression format.
+//!
+//!
+//! # Examples
+//!
+//! ```
+//! # #[cfg(feature = "xz")] {
+//! use std::io::Write;
+//!
+//! use gzp::{xz::Xz, par::compress::{ParCompress, ParCompressBuilder}, ZWriter};
+//!
+//! let mut writer = vec![];
+//! let mut parz: ParCompress<Xz,_> = ParCompressBuilder::new().from_writer(writer);
+//! parz.write_all(b"This is a first test line\n").unwrap();
+//! parz.write_all(b"This is a second test line\n").unwrap();
+//! parz.finish().unwrap();
+//! # }
+//! ```
+
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use flate2::Compression;
+use xz2::stream::{MtStreamBuilder, Stream};
+use xz2::write::XzEncoder;
+
+use crate::{
+    buffer::{CoalescingWriter, DEFAULT_BUFFER_SIZE},
+    check::PassThroughCheck,
+    syncz::SyncZ,
+    FormatSpec, GzpError, SyncWriter, ZWriter,
+};
+
+/// The largest xz preset accepted by `xz2::stream::LzmaOptions::new_preset`.
+const MAX_PRESET: u32 = 9;
+
+/// Cap on the number of threads handed to the multithreaded xz encoder, matching the limit
+/// `rust-installer` applies so 32-bit hosts don't exhaust their address space: each worker
+/// stream needs its own dictionary-sized buffer, so an unbounded thread count can blow out
+/// memory well before it helps throughput.
+const MAX_MT_THREADS: usize = 8;
+
+/// Map a [`Compression`] level (0-9) onto an xz preset in the same range.
+#[inline]
+fn preset(compression_level: Compression) -> u32 {
+    compression_level.level().min(MAX_PRESET)
+}
+
+/// Number of worker threads to give the multithreaded xz encoder.
+#[inline]
+fn num_mt_threads() -> u32 {
+    num_cpus::get().min(MAX_MT_THREADS) as u32
+}
+
+/// Xz / LZMA format.
+#[derive(Copy, Clone, Debug)]
+pub struct Xz {}
+
+impl FormatSpec for Xz {
+    type C = PassThroughCheck;
+    // There is nothing to carry between blocks: each worker block becomes its own independent
+    // xz stream, so `encode` builds a fresh `Stream` every call rather than reusing state.
+    type Compressor = ();
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    #[inline]
+    fn create_compressor(&self, _compression_level: Compression) -> Result<Self::Compressor, GzpError> {
+        Ok(())
+    }
+
+    #[inline]
+    fn needs_dict(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    #[allow(unused)]
+    fn encode(
+        &self,
+        input: &[u8],
+        encoder: &mut Self::Compressor,
+        compression_level: Compression,
+        dict: Option<&Bytes>,
+        is_last: bool,
+    ) -> Result<Vec<u8>, GzpError> {
+        // Each worker block is encoded as its own independent xz stream: like zstd's independent
+        // frames, a decoder reading concatenated xz streams decodes them transparently.
+        //
+        // `xz2::stream::Error` has no `From` impl onto `GzpError`, so map it by hand the same way
+        // `rayon::ThreadPoolBuildError` is handled in the zstd parallel decompressor.
+        let stream = Stream::new_easy_encoder(preset(compression_level), xz2::stream::Check::None)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let mut writer = XzEncoder::new_stream(Vec::new(), stream);
+        writer.write_all(input)?;
+        Ok(writer.finish()?)
+    }
+
+    fn header(&self, _compression_level: Compression) -> Vec<u8> {
+        vec![]
+    }
+
+    fn footer(&self, _check: &Self::C) -> Vec<u8> {
+        vec![]
+    }
+}
+
+impl<W> SyncWriter<W> for Xz
+where
+    W: Write,
+{
+    // Same reasoning as `zstd::Zstd`'s `SyncWriter` impl: coalesce the caller's small
+    // `write_all` calls in front of the codec rather than leaving them to hit `MtStreamBuilder`'s
+    // encoder one at a time.
+    type OutputWriter = CoalescingWriter<XzEncoder<W>>;
+
+    fn sync_writer(writer: W, compression_level: Compression) -> CoalescingWriter<XzEncoder<W>> {
+        let stream = MtStreamBuilder::new()
+            .threads(num_mt_threads())
+            .preset(preset(compression_level))
+            .encoder()
+            .unwrap();
+        let encoder = XzEncoder::new_stream(writer, stream);
+        CoalescingWriter::new(encoder, DEFAULT_BUFFER_SIZE)
+    }
+}
+
+impl<W: Write> ZWriter<W> for SyncZ<CoalescingWriter<XzEncoder<W>>> {
+    fn finish(&mut self) -> Result<W, GzpError> {
+        let encoder = self.inner.take().unwrap().finish()?;
+        Ok(encoder.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::{
+        fs::File,
+        io::{BufReader, BufWriter},
+    };
+
+    use tempfile::tempdir;
+    use xz2::read::XzDecoder;
+
+    use crate::par::compress::{ParCompress, ParCompressBuilder};
+    use crate::ZWriter;
+
+    use super::*;
+
+    #[test]
+    fn test_simple() {
+        let dir = tempdir().unwrap();
+
+        // Create output file
+        let output_file = dir.path().join("output.txt");
+        let out_writer = BufWriter::new(File::create(&output_file).unwrap());
+
+        // Define input bytes
+        let input = b"
+        This is a longer test than normal to come up with a bunch of text.
+        We'll read just a few lines at a time.
+        ";
+
+        // Compress input to output
+        let mut par_xz: ParCompress<Xz, _> = ParCompressBuilder::new().from_writer(out_writer);
+        par_xz.write_all(input).unwrap();
+        par_xz.finish().unwrap();
+
+        // Read output back in
+        let mut reader = BufReader::new(File::open(output_file).unwrap());
+        let mut result = vec![];
+        reader.read_to_end(&mut result).unwrap();
+
+        // Decompress it
+        let mut xz = XzDecoder::new(&result[..]);
+        let mut bytes = vec![];
+        xz.read_to_end(&mut bytes).unwrap();
+
+        // Assert decompressed output is equal to input
+        assert_eq!(input.to_vec(), bytes);
+    }
+}